@@ -1,25 +1,85 @@
-use bevy::input::common_conditions::input_just_released;
+use bevy::core_pipeline::bloom::Bloom;
+use bevy::core_pipeline::tonemapping::Tonemapping;
+use bevy::ecs::system::SystemParam;
+use bevy::input::common_conditions::{input_just_pressed, input_just_released};
 use bevy::input::mouse::AccumulatedMouseMotion;
 use bevy::prelude::*;
+use bevy::time::Stopwatch;
 use bevy::window::{PrimaryWindow, WindowFocused};
 use rand::SeedableRng;
 use std::f32::consts::PI;
 
-const PLAYER_SPEED: f32 = 50.;
-const MOUSE_SENSITIVITY: f32 = 0.01;
-const SHOT_VELOCITY: f32 = 10.;
-const GRAVITY: Vec3 = Vec3::new(0., -9.8, 0.);
-const POWER_MIN: f32 = 1.;
-const POWER_MAX: f32 = 6.;
+const RESTITUTION_DECAY: f32 = 0.9;
+const REST_SPEED: f32 = 0.05;
+const REST_SECS: f32 = 2.;
+const REST_HEIGHT: f32 = 0.1;
+const REST_BOUNCE_SPEED: f32 = 0.5;
+const TRAJECTORY_POINTS: usize = 40;
+const TRAJECTORY_MAX_STEPS: usize = 600;
+const BURST_BALLS_PER_SECOND: f32 = 4.;
+const MAX_BURST: u32 = 20;
+const BURST_INTERVAL: f32 = 0.05;
+const BURST_POWER: f32 = 3.;
+const FLICK_WINDOW: f32 = 0.1;
+const FLICK_INFLUENCE: f32 = 0.0001;
 
+const LASER_ORIGIN: Vec3 = Vec3::new(0.25, -0.15, 0.);
+const EYE_HEIGHT: f32 = 1.6;
+
+const VIEW_BOUNDS_DISTANCE: f32 = 50.;
+const VIEW_BOUNDS_MARGIN: f32 = 0.05;
+const MAX_OFFSCREEN_ARROWS: usize = 8;
+const OFFSCREEN_ARROW_INSET: f32 = 24.;
+const MAX_CHARGE_DIP: f32 = 0.1;
+const MUZZLE_FLASH_SECS: f32 = 1.;
+const CONFETTI_PIECES: usize = 24;
+const CONFETTI_SECS: f32 = 1.5;
+const CONFETTI_SPEED: f32 = 3.;
+const CONFETTI_SIZE: f32 = 0.08;
+
+const TONEMAPPING_LABEL_SECS: f32 = 1.5;
+const TONEMAPPING_CYCLE: [Tonemapping; 8] = [
+    Tonemapping::None,
+    Tonemapping::Reinhard,
+    Tonemapping::ReinhardLuminance,
+    Tonemapping::AcesFitted,
+    Tonemapping::AgX,
+    Tonemapping::SomewhatBoringDisplayTransform,
+    Tonemapping::TonyMcMapface,
+    Tonemapping::BlenderFilmic,
+];
+
+const BUTTON_IDLE: Color = Color::linear_rgb(0.15, 0.15, 0.15);
+const BUTTON_HOVERED: Color = Color::linear_rgb(0.3, 0.3, 0.3);
+const CROSSHAIR: Color = Color::linear_rgba(1., 1., 1., 0.8);
 const NOT_CHARGING: Color = Color::linear_rgb(0.2, 0.2, 0.2);
-const MIN_FILL: f32 = 29.75 / POWER_MAX;
-const EMPTY_SPACE: f32 = 29.75 - MIN_FILL;
+const BAR_LENGTH: f32 = 29.75;
 
 fn main() {
     let mut app = App::new();
     app.add_plugins(DefaultPlugins);
-    app.add_systems(Startup, (spawn_camera, spawn_map));
+    app.init_state::<GameState>();
+    app.add_computed_state::<InGame>();
+    app.enable_state_scoped_entities::<GameState>();
+    app.enable_state_scoped_entities::<InGame>();
+    app.add_systems(Startup, spawn_camera);
+    app.add_systems(
+        OnEnter(InGame),
+        (
+            spawn_map,
+            spawn_initial_balls.after(spawn_map),
+            spawn_turret,
+        ),
+    );
+    app.add_systems(OnEnter(GameState::Playing), spawn_hud);
+    app.add_systems(OnEnter(GameState::Menu), spawn_menu);
+    app.add_systems(
+        OnEnter(GameState::Paused),
+        (release_grab, cancel_charge, spawn_pause_overlay),
+    );
+    app.add_systems(OnExit(GameState::Paused), restore_grab);
+    app.add_systems(OnEnter(GameState::Menu), release_grab);
+    app.add_systems(OnExit(GameState::Menu), restore_grab);
     app.insert_resource(Time::<Fixed>::from_hz(60.));
     app.add_systems(
         FixedUpdate,
@@ -27,33 +87,222 @@ fn main() {
             apply_velocity,
             apply_gravity.before(apply_velocity),
             bounce.after(apply_velocity),
-        ),
+            contain_in_view
+                .after(apply_velocity)
+                .before(ball_collisions)
+                .before(bounce)
+                .run_if(resource_equals(ViewBounds(true))),
+            ball_collisions.after(apply_velocity).before(bounce),
+            despawn_balls.after(bounce).after(ball_collisions),
+        )
+            .run_if(in_state(GameState::Playing).or(resource_equals(StepOnce(true))))
+            .run_if(resource_equals(PhysicsSchedule::Fixed)),
+    );
+    app.add_systems(FixedUpdate, consume_step_once.after(despawn_balls));
+    app.add_systems(
+        Update,
+        (
+            apply_velocity,
+            apply_gravity.before(apply_velocity),
+            bounce.after(apply_velocity),
+            contain_in_view
+                .after(apply_velocity)
+                .before(ball_collisions)
+                .before(bounce)
+                .run_if(resource_equals(ViewBounds(true))),
+            ball_collisions.after(apply_velocity).before(bounce),
+            despawn_balls.after(bounce).after(ball_collisions),
+        )
+            .run_if(in_state(GameState::Playing))
+            .run_if(resource_equals(PhysicsSchedule::Variable)),
     );
     app.add_systems(
         Update,
         (
-            player_look,
-            player_move.after(player_look),
-            focus_events,
-            toggle_grab.run_if(input_just_released(KeyCode::Escape)),
-            shoot_ball.before(spawn_ball).before(focus_events),
-            spawn_ball,
+            (
+                player_look,
+                player_move.after(player_look),
+                focus_events,
+                track_mouse_flick
+                    .before(shoot_ball)
+                    .run_if(resource_equals(FlickEnglish(true))),
+                shoot_ball
+                    .run_if(cursor_grabbed)
+                    .before(spawn_ball)
+                    .before(focus_events),
+                spawn_ball,
+                click_to_grab
+                    .after(shoot_ball)
+                    .run_if(resource_equals(ClickToGrab(true)))
+                    .run_if(input_just_pressed(MouseButton::Left)),
+                draw_trajectory.after(shoot_ball),
+                toggle_trajectory_bounces.run_if(input_just_pressed(KeyCode::KeyJ)),
+                count_spawns,
+                reset_balls.run_if(input_just_pressed(KeyCode::KeyR)),
+                update_stats_text.after(count_spawns).after(reset_balls),
+            )
+                .run_if(in_state(GameState::Playing)),
+            toggle_pause.run_if(input_just_released(KeyCode::Escape)),
+            auto_pause_on_blur.run_if(resource_equals(AutoPauseOnBlur(true))),
+            resume
+                .run_if(in_state(GameState::Paused))
+                .run_if(input_just_pressed(MouseButton::Left)),
+            quit_to_menu
+                .run_if(in_state(GameState::Paused))
+                .run_if(input_just_pressed(KeyCode::KeyQ)),
+            step_once
+                .run_if(in_state(GameState::Paused))
+                .run_if(input_just_pressed(KeyCode::Period)),
+            press_play.run_if(in_state(GameState::Menu)),
+            toggle_power_bar_orientation.run_if(input_just_pressed(KeyCode::KeyV)),
+            layout_power_bar
+                .run_if(resource_changed::<PowerBarOrientation>)
+                .after(toggle_power_bar_orientation)
+                .before(update_power_bar),
             update_power_bar,
+            show_grabbed_hud,
+            cycle_tonemapping.run_if(input_just_pressed(KeyCode::KeyT)),
+            toggle_bloom.run_if(input_just_pressed(KeyCode::KeyB)),
+            fade_tonemapping_label,
+            toggle_laser_sight.run_if(input_just_pressed(KeyCode::KeyL)),
+            draw_laser_sight,
+        ),
+    );
+    app.add_systems(
+        Update,
+        (
+            toggle_debug_gizmos.run_if(input_just_pressed(KeyCode::F3)),
+            mark_muzzle.run_if(resource_equals(DebugGizmos(true))),
+            draw_muzzle_flashes,
+            apply_charge_dip.after(player_move).after(shoot_ball),
+            toggle_ball_shadows.run_if(input_just_pressed(KeyCode::KeyG)),
+            draw_ball_shadows.run_if(resource_equals(BallShadows(true))),
+            cycle_charge_curve.run_if(input_just_pressed(KeyCode::KeyK)),
+            toggle_charge_mode.run_if(input_just_pressed(KeyCode::KeyN)),
+            cycle_ball_color.run_if(input_just_pressed(KeyCode::KeyC)),
+            toggle_physics_schedule.run_if(input_just_pressed(KeyCode::F5)),
+            toggle_shot_origin
+                .run_if(in_state(InGame))
+                .run_if(input_just_pressed(KeyCode::KeyY)),
+            aim_turret.after(player_look),
+            toggle_view_bounds.run_if(input_just_pressed(KeyCode::KeyF)),
+            toggle_float_until_bounce.run_if(input_just_pressed(KeyCode::KeyM)),
+            point_offscreen_arrows.after(player_move),
+            cycle_bounce_profile.run_if(input_just_pressed(KeyCode::KeyX)),
+            toggle_confetti.run_if(input_just_pressed(KeyCode::KeyP)),
+            toggle_flick_english.run_if(input_just_pressed(KeyCode::KeyE)),
+            pop_confetti.run_if(resource_equals(ConfettiMode(true))),
+            draw_confetti.after(pop_confetti),
         ),
     );
     app.add_observer(apply_grab);
     app.add_event::<BallSpawn>();
+    app.add_event::<BallRested>();
+    app.insert_resource(WorldScale(1.));
     app.init_resource::<BallData>();
+    app.insert_resource(GameSettings::load());
+    app.init_resource::<MouseFlick>();
+    app.init_resource::<FlickEnglish>();
+    app.init_resource::<PowerBarOrientation>();
+    app.init_resource::<LaserSight>();
+    app.init_resource::<InitialBalls>();
+    app.init_resource::<FixedBallColor>();
+    app.init_resource::<PhysicsSchedule>();
+    app.init_resource::<ShotOrigin>();
+    app.init_resource::<ViewBounds>();
+    app.init_resource::<FloatUntilBounce>();
+    app.init_resource::<BounceSettings>();
+    app.init_resource::<Arena>();
+    app.init_resource::<BounceProfile>();
+    app.insert_resource(BounceGain(1.2));
+    app.init_resource::<DebugGizmos>();
+    app.insert_resource(ClickToGrab(true));
+    app.insert_resource(BallCap {
+        max_live: Some(500),
+        max_age: 60.,
+    });
+    app.init_resource::<LiveBalls>();
+    app.init_resource::<SpawnStats>();
+    app.init_resource::<StepOnce>();
+    app.init_resource::<AutoPauseOnBlur>();
+    app.init_resource::<ConfettiMode>();
+    app.init_resource::<TrajectoryBounces>();
+    app.init_resource::<CollisionIterations>();
+    app.insert_resource(BallShadows(true));
     app.insert_resource(Power {
         charging: false,
         current: 0.,
+        held: 0.,
+        curve: ChargeCurve::Linear,
+        mode: ChargeMode::Power,
+        burst: 0,
+        burst_cooldown: 0.,
     });
     app.run();
 }
 
+#[derive(Resource, serde::Deserialize)]
+#[serde(default)]
+struct GameSettings {
+    player_speed: f32,
+    mouse_sensitivity: f32,
+    shot_velocity: f32,
+    gravity: Vec3,
+    gravity_scale: f32,
+    power_min: f32,
+    power_max: f32,
+}
+
+impl Default for GameSettings {
+    fn default() -> Self {
+        GameSettings {
+            player_speed: 50.,
+            mouse_sensitivity: 0.01,
+            shot_velocity: 10.,
+            gravity: Vec3::new(0., -9.8, 0.),
+            gravity_scale: 1.,
+            power_min: 1.,
+            power_max: 6.,
+        }
+    }
+}
+
+impl GameSettings {
+    fn load() -> Self {
+        let Some(path) = std::env::current_exe()
+            .ok()
+            .and_then(|exe| Some(exe.parent()?.join("settings.ron")))
+        else {
+            return GameSettings::default();
+        };
+        let Ok(text) = std::fs::read_to_string(&path) else {
+            return GameSettings::default();
+        };
+        ron::from_str(&text).unwrap_or_else(|err| {
+            warn!("ignoring malformed {}: {err}", path.display());
+            GameSettings::default()
+        })
+    }
+
+    fn power_range(&self) -> f32 {
+        self.power_max - self.power_min
+    }
+
+    fn max_launch_speed(&self) -> f32 {
+        self.shot_velocity * self.shot_velocity * self.power_max
+    }
+
+    fn min_fill(&self) -> f32 {
+        BAR_LENGTH * self.power_min / self.power_max
+    }
+}
+
 #[derive(Component, Deref, DerefMut)]
 struct Velocity(Vec3);
 
+#[derive(Component, Deref, DerefMut)]
+struct GravityScale(f32);
+
 #[derive(Component)]
 struct Player;
 
@@ -71,23 +320,60 @@ struct BallSpawn {
 struct BallData {
     mesh: Handle<Mesh>,
     materials: Vec<Handle<StandardMaterial>>,
+    fallback_material: Handle<StandardMaterial>,
     rng: std::sync::Mutex<rand::rngs::StdRng>,
 }
 
 impl BallData {
+    fn new(
+        mesh: Handle<Mesh>,
+        materials: Vec<Handle<StandardMaterial>>,
+        fallback_material: Handle<StandardMaterial>,
+    ) -> Self {
+        let seed = *b"DaverinoeIsC00lDaverinoeIsC00l22";
+        BallData {
+            mesh,
+            materials,
+            fallback_material,
+            rng: std::sync::Mutex::new(rand::rngs::StdRng::from_seed(seed)),
+        }
+    }
     fn mesh(&self) -> Handle<Mesh> {
         self.mesh.clone()
     }
     fn material(&self) -> Handle<StandardMaterial> {
         use rand::seq::IndexedRandom;
         let mut rng = self.rng.lock().unwrap();
-        self.materials.choose(&mut rng).unwrap().clone()
+        self.materials
+            .choose(&mut rng)
+            .unwrap_or(&self.fallback_material)
+            .clone()
+    }
+    fn ball(
+        &self,
+        position: Vec3,
+        velocity: Vec3,
+        material: Handle<StandardMaterial>,
+    ) -> impl Bundle {
+        (
+            Transform::from_translation(position),
+            Mesh3d(self.mesh()),
+            MeshMaterial3d(material),
+            Velocity(velocity),
+            HasBounced(false),
+            CurrentRestitution(1.),
+            StateScoped(InGame),
+            Ball,
+            BallLifetime::default(),
+            RestTimer(Timer::from_seconds(REST_SECS, TimerMode::Once)),
+        )
     }
 }
 
 impl FromWorld for BallData {
     fn from_world(world: &mut World) -> Self {
-        let mesh = world.resource_mut::<Assets<Mesh>>().add(Sphere::new(1.));
+        let scale = world.get_resource::<WorldScale>().map_or(1., |s| **s);
+        let mesh = world.resource_mut::<Assets<Mesh>>().add(Sphere::new(scale));
         let mut materials = Vec::new();
         let mut mat_assets = world.resource_mut::<Assets<StandardMaterial>>();
         for i in 0..36 {
@@ -97,87 +383,726 @@ impl FromWorld for BallData {
                 ..Default::default()
             }));
         }
-        let seed = *b"DaverinoeIsC00lDaverinoeIsC00l22";
-        BallData {
-            mesh,
-            materials,
-            rng: std::sync::Mutex::new(rand::rngs::StdRng::from_seed(seed)),
+        let fallback_material = mat_assets.add(StandardMaterial::from_color(Color::WHITE));
+        BallData::new(mesh, materials, fallback_material)
+    }
+}
+
+fn apply_gravity(
+    mut objects: Query<(&mut Velocity, Option<&GravityScale>, Option<&HasBounced>)>,
+    float_until_bounce: Res<FloatUntilBounce>,
+    settings: Res<GameSettings>,
+    scale: Res<WorldScale>,
+    time: Res<Time>,
+) {
+    let g = settings.gravity * **scale * time.delta_secs();
+    objects.par_iter_mut().for_each(|(mut v, scale, bounced)| {
+        if float_until_bounce.0 && bounced.is_some_and(|b| !b.0) {
+            return;
+        }
+        **v += g * scale.map_or(1., |s| **s);
+    });
+}
+
+fn bounce(
+    mut balls: Query<(
+        &mut Transform,
+        &mut Velocity,
+        Option<&mut HasBounced>,
+        Option<&mut CurrentRestitution>,
+    )>,
+    arena: ArenaBounce,
+) {
+    for (mut transform, mut velocity, bounced, mut restitution) in &mut balls {
+        let hit = arena.reflect(
+            &mut transform.translation,
+            &mut velocity,
+            restitution.as_mut().map(|r| &mut r.0),
+        );
+        if hit.floor
+            && let Some(mut bounced) = bounced
+        {
+            bounced.0 = true;
         }
     }
 }
 
-fn apply_gravity(mut objects: Query<&mut Velocity>, time: Res<Time>) {
-    let g = GRAVITY * time.delta_secs();
-    for mut v in &mut objects {
-        **v += g;
+#[derive(SystemParam)]
+struct ArenaBounce<'w> {
+    settings: Res<'w, BounceSettings>,
+    game: Res<'w, GameSettings>,
+    profile: Res<'w, BounceProfile>,
+    gain: Res<'w, BounceGain>,
+    arena: Res<'w, Arena>,
+    scale: Res<'w, WorldScale>,
+}
+
+#[derive(Default)]
+struct ArenaHit {
+    any: bool,
+    floor: bool,
+}
+
+impl ArenaBounce<'_> {
+    // Shared by `bounce` and the trajectory preview so the preview can't drift
+    // from what a fired ball actually does.
+    fn reflect(
+        &self,
+        position: &mut Vec3,
+        velocity: &mut Vec3,
+        mut restitution: Option<&mut f32>,
+    ) -> ArenaHit {
+        let settings = &self.settings;
+        let min = self.arena.min * **self.scale;
+        let max = self.arena.max * **self.scale;
+        let mut restitute = |speed: &mut f32, floor: bool| {
+            *speed *= -1.;
+            match *self.profile {
+                BounceProfile::Constant => *speed *= settings.restitution,
+                BounceProfile::Gain => *speed *= **self.gain,
+                BounceProfile::Decay => {
+                    if let Some(restitution) = restitution.as_deref_mut() {
+                        *speed *= settings.restitution * *restitution;
+                        if floor {
+                            *restitution *= RESTITUTION_DECAY;
+                        }
+                    }
+                }
+            }
+        };
+        let mut hit = ArenaHit::default();
+
+        if position.y < min.y && velocity.y < 0. {
+            position.y = min.y;
+            restitute(&mut velocity.y, true);
+            velocity.x *= settings.friction;
+            velocity.z *= settings.friction;
+            if velocity.y < settings.rest_speed * **self.scale {
+                velocity.y = 0.;
+            }
+            hit.floor = true;
+        }
+        if self.arena.ceiling && position.y > max.y && velocity.y > 0. {
+            position.y = max.y;
+            restitute(&mut velocity.y, false);
+            hit.any = true;
+        }
+        for axis in [0, 2] {
+            let speed = &mut velocity[axis];
+            if (position[axis] < min[axis] && *speed < 0.)
+                || (position[axis] > max[axis] && *speed > 0.)
+            {
+                position[axis] = position[axis].clamp(min[axis], max[axis]);
+                restitute(speed, false);
+                hit.any = true;
+            }
+        }
+        hit.any |= hit.floor;
+
+        if hit.any && *self.profile == BounceProfile::Gain {
+            // Cap gained speed at the hardest possible shot, so gain never
+            // takes energy away from a shot that is already fast.
+            let cap = self.game.max_launch_speed() * **self.scale;
+            *velocity = velocity.clamp_length_max(cap);
+        }
+        hit
     }
 }
 
-fn bounce(mut balls: Query<(&Transform, &mut Velocity)>) {
-    for (transform, mut velocity) in &mut balls {
-        if transform.translation.y < 0. && velocity.y < 0. {
-            velocity.y *= -1.;
+#[derive(Resource)]
+struct Arena {
+    min: Vec3,
+    max: Vec3,
+    ceiling: bool,
+}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Arena {
+            min: Vec3::new(-60., 0., -60.),
+            max: Vec3::new(60., 40., 20.),
+            ceiling: false,
+        }
+    }
+}
+
+#[derive(Resource)]
+struct BounceSettings {
+    restitution: f32,
+    friction: f32,
+    rest_speed: f32,
+}
+
+impl Default for BounceSettings {
+    fn default() -> Self {
+        BounceSettings {
+            restitution: 0.7,
+            friction: 0.95,
+            rest_speed: 0.5,
+        }
+    }
+}
+
+#[derive(Resource, Default, PartialEq, Clone, Copy)]
+enum BounceProfile {
+    #[default]
+    Constant,
+    Gain,
+    Decay,
+}
+
+fn cycle_bounce_profile(mut profile: ResMut<BounceProfile>) {
+    *profile = match *profile {
+        BounceProfile::Constant => BounceProfile::Gain,
+        BounceProfile::Gain => BounceProfile::Decay,
+        BounceProfile::Decay => BounceProfile::Constant,
+    };
+}
+
+#[derive(Component, Deref, DerefMut)]
+struct CurrentRestitution(f32);
+
+#[derive(Resource, Deref)]
+struct BounceGain(f32);
+
+#[derive(Component)]
+struct Ball;
+
+#[derive(Component, Default)]
+struct BallLifetime(Stopwatch);
+
+#[derive(Component)]
+struct RestTimer(Timer);
+
+#[derive(Resource)]
+struct BallCap {
+    max_live: Option<usize>,
+    max_age: f32,
+}
+
+#[derive(Resource, Default, Deref)]
+struct LiveBalls(usize);
+
+fn despawn_balls(
+    mut balls: Query<
+        (
+            Entity,
+            &Transform,
+            &Velocity,
+            &mut BallLifetime,
+            &mut RestTimer,
+        ),
+        With<Ball>,
+    >,
+    mut commands: Commands,
+    mut rested: EventWriter<BallRested>,
+    mut live: ResMut<LiveBalls>,
+    cap: Res<BallCap>,
+    scale: Res<WorldScale>,
+    time: Res<Time>,
+) {
+    let mut alive = Vec::new();
+    for (entity, transform, velocity, mut lifetime, mut rest) in &mut balls {
+        // A ball sitting on the floor never reaches zero speed: gravity pulls it
+        // under y = 0 each tick and bounce flips it back up. Ignore that jitter.
+        let grounded = transform.translation.y <= REST_HEIGHT * **scale
+            && velocity.y.abs() < REST_BOUNCE_SPEED * **scale;
+        let speed = if grounded {
+            velocity.xz().length()
+        } else {
+            velocity.length()
+        };
+        if speed < REST_SPEED * **scale {
+            rest.0.tick(time.delta());
+        } else {
+            rest.0.reset();
+        }
+        lifetime.0.tick(time.delta());
+        if rest.0.finished() {
+            rested.write(BallRested {
+                position: transform.translation,
+            });
+        }
+        if rest.0.finished() || lifetime.0.elapsed_secs() >= cap.max_age {
+            commands.entity(entity).despawn();
+        } else {
+            alive.push((entity, lifetime.0.elapsed()));
+        }
+    }
+    if let Some(cap) = cap.max_live
+        && alive.len() > cap
+    {
+        alive.sort_by_key(|(_, age)| std::cmp::Reverse(*age));
+        for (entity, _) in alive.drain(..alive.len() - cap) {
+            commands.entity(entity).despawn();
+        }
+    }
+    live.0 = alive.len();
+}
+
+#[derive(Event)]
+struct BallRested {
+    position: Vec3,
+}
+
+#[derive(Resource, Default, PartialEq)]
+struct ConfettiMode(bool);
+
+fn toggle_confetti(mut mode: ResMut<ConfettiMode>) {
+    mode.0 = !mode.0;
+}
+
+#[derive(Component)]
+struct Confetti {
+    velocity: Vec3,
+    spin: Vec3,
+    color: Color,
+    life: Timer,
+}
+
+fn pop_confetti(
+    mut events: EventReader<BallRested>,
+    mut commands: Commands,
+    scale: Res<WorldScale>,
+) {
+    use rand::Rng;
+    let mut rng = rand::rng();
+    for rested in events.read() {
+        for _ in 0..CONFETTI_PIECES {
+            let direction = Vec3::new(
+                rng.random_range(-1.0..1.),
+                rng.random_range(0.5..1.5),
+                rng.random_range(-1.0..1.),
+            );
+            commands.spawn((
+                Transform::from_translation(rested.position),
+                Confetti {
+                    velocity: direction * CONFETTI_SPEED * **scale,
+                    spin: Vec3::new(
+                        rng.random_range(-10.0..10.),
+                        rng.random_range(-10.0..10.),
+                        rng.random_range(-10.0..10.),
+                    ),
+                    color: Color::hsl(rng.random_range(0.0..360.), 0.9, 0.6),
+                    life: Timer::from_seconds(CONFETTI_SECS, TimerMode::Once),
+                },
+            ));
+        }
+    }
+}
+
+fn draw_confetti(
+    mut pieces: Query<(Entity, &mut Transform, &mut Confetti)>,
+    mut gizmos: Gizmos,
+    mut commands: Commands,
+    settings: Res<GameSettings>,
+    scale: Res<WorldScale>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_secs();
+    for (entity, mut transform, mut piece) in &mut pieces {
+        if piece.life.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        piece.velocity += settings.gravity * **scale * dt;
+        transform.translation += piece.velocity * dt;
+        transform.translation.y = transform.translation.y.max(0.);
+        transform.rotate(Quat::from_scaled_axis(piece.spin * dt));
+        gizmos.rect(
+            Isometry3d::new(transform.translation, transform.rotation),
+            Vec2::splat(CONFETTI_SIZE * **scale),
+            piece.color.with_alpha(piece.life.fraction_remaining()),
+        );
+    }
+}
+
+#[derive(Resource, Deref)]
+struct CollisionIterations(u32);
+
+impl Default for CollisionIterations {
+    fn default() -> Self {
+        CollisionIterations(1)
+    }
+}
+
+fn ball_collisions(
+    mut balls: Query<(Entity, &mut Transform, &mut Velocity), With<Ball>>,
+    iterations: Res<CollisionIterations>,
+    scale: Res<WorldScale>,
+) {
+    let radius = **scale;
+    let cell_size = radius * 2.;
+    let cell_of = |position: Vec3| (position / cell_size).floor().as_ivec3();
+
+    let bodies: Vec<(Entity, Vec3, Vec3)> = balls
+        .iter()
+        .map(|(entity, transform, velocity)| (entity, transform.translation, **velocity))
+        .collect();
+    let mut positions: Vec<Vec3> = bodies.iter().map(|b| b.1).collect();
+    let mut velocities: Vec<Vec3> = bodies.iter().map(|b| b.2).collect();
+
+    for _ in 0..**iterations {
+        let mut grid: std::collections::HashMap<IVec3, Vec<usize>> = Default::default();
+        for (i, position) in positions.iter().enumerate() {
+            grid.entry(cell_of(*position)).or_default().push(i);
+        }
+        for i in 0..positions.len() {
+            let cell = cell_of(positions[i]);
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        let Some(neighbours) = grid.get(&(cell + IVec3::new(dx, dy, dz))) else {
+                            continue;
+                        };
+                        for &j in neighbours.iter().filter(|&&j| j > i) {
+                            let between = positions[j] - positions[i];
+                            let distance = between.length();
+                            if distance >= radius * 2. {
+                                continue;
+                            }
+                            let normal = between.try_normalize().unwrap_or(Vec3::Y);
+                            let overlap = radius * 2. - distance;
+                            positions[i] -= normal * overlap / 2.;
+                            positions[j] += normal * overlap / 2.;
+                            let closing = (velocities[i] - velocities[j]).dot(normal);
+                            if closing > 0. {
+                                velocities[i] -= normal * closing;
+                                velocities[j] += normal * closing;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    for (i, (entity, position, velocity)) in bodies.iter().enumerate() {
+        if positions[i] == *position && velocities[i] == *velocity {
+            continue;
+        }
+        if let Ok((_, mut transform, mut velocity)) = balls.get_mut(*entity) {
+            transform.translation = positions[i];
+            **velocity = velocities[i];
         }
     }
 }
 
-fn spawn_camera(mut commands: Commands) {
-    commands.spawn((Camera3d::default(), Player));
+#[derive(Component)]
+struct HasBounced(bool);
+
+#[derive(Resource, Default)]
+struct FloatUntilBounce(bool);
+
+fn toggle_float_until_bounce(mut float_until_bounce: ResMut<FloatUntilBounce>) {
+    float_until_bounce.0 = !float_until_bounce.0;
+}
+
+fn spawn_camera(mut commands: Commands, scale: Res<WorldScale>) {
+    commands.spawn((
+        Camera3d::default(),
+        Transform::from_xyz(0., EYE_HEIGHT * **scale, 0.),
+        Player,
+        ViewOffset(Vec3::ZERO),
+    ));
 }
 
-fn spawn_map(mut commands: Commands, ball_data: Res<BallData>) {
-    commands.spawn(DirectionalLight::default());
+fn spawn_map(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    ball_data: Res<BallData>,
+    arena: Res<Arena>,
+    scale: Res<WorldScale>,
+) {
+    commands.spawn((DirectionalLight::default(), StateScoped(InGame)));
+
+    let min = arena.min * **scale;
+    let max = arena.max * **scale;
+    let center = (min + max) / 2.;
+    let size = max - min;
+    let thickness = 0.5 * **scale;
+    let wall_material = materials.add(StandardMaterial {
+        base_color: Color::linear_rgba(0.6, 0.7, 0.8, 0.25),
+        alpha_mode: AlphaMode::Blend,
+        ..Default::default()
+    });
+    let mut walls = vec![
+        (
+            Vec3::new(min.x, center.y, center.z),
+            Vec3::new(thickness, size.y, size.z),
+        ),
+        (
+            Vec3::new(max.x, center.y, center.z),
+            Vec3::new(thickness, size.y, size.z),
+        ),
+        (
+            Vec3::new(center.x, center.y, min.z),
+            Vec3::new(size.x, size.y, thickness),
+        ),
+        (
+            Vec3::new(center.x, center.y, max.z),
+            Vec3::new(size.x, size.y, thickness),
+        ),
+    ];
+    if arena.ceiling {
+        walls.push((
+            Vec3::new(center.x, max.y, center.z),
+            Vec3::new(size.x, thickness, size.z),
+        ));
+    }
+    for (position, size) in walls {
+        commands.spawn((
+            Transform::from_translation(position),
+            Mesh3d(meshes.add(Cuboid::from_size(size))),
+            MeshMaterial3d(wall_material.clone()),
+            StateScoped(InGame),
+        ));
+    }
 
     for h in 0..ball_data.materials.len() {
         let ball_material = ball_data.materials[h].clone();
         commands.spawn((
-            Transform::from_translation(Vec3::new((-8. + h as f32) * 2., 0., -50.)),
+            Transform::from_translation(Vec3::new((-8. + h as f32) * 2., 0., -50.) * **scale),
             Mesh3d(ball_data.mesh()),
             MeshMaterial3d(ball_material),
+            StateScoped(InGame),
         ));
     }
-    commands
-        .spawn((
+}
+
+fn spawn_hud(
+    mut commands: Commands,
+    orientation: Res<PowerBarOrientation>,
+    settings: Res<GameSettings>,
+) {
+    for _ in 0..MAX_OFFSCREEN_ARROWS {
+        commands.spawn((
             Node {
                 position_type: PositionType::Absolute,
-                width: Val::VMax(30.),
-                height: Val::VMax(5.),
-                bottom: Val::Px(20.),
-                left: Val::Px(20.),
+                width: Val::Px(28.),
+                height: Val::Px(6.),
                 ..Default::default()
             },
+            BackgroundColor(Color::linear_rgb(1., 0.8, 0.)),
+            BorderRadius::all(Val::Px(3.)),
+            Visibility::Hidden,
+            OffscreenArrow,
+            StateScoped(GameState::Playing),
+        ));
+    }
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(20.),
+            right: Val::Px(20.),
+            ..Default::default()
+        },
+        Text::default(),
+        Visibility::Hidden,
+        TonemappingLabel(Timer::from_seconds(TONEMAPPING_LABEL_SECS, TimerMode::Once)),
+        StateScoped(GameState::Playing),
+    ));
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(20.),
+            left: Val::Px(20.),
+            padding: UiRect::axes(Val::Px(10.), Val::Px(6.)),
+            ..Default::default()
+        },
+        BackgroundColor(Color::linear_rgba(0., 0., 0., 0.6)),
+        BorderRadius::all(Val::Px(6.)),
+        Text::default(),
+        StatsText,
+        StateScoped(GameState::Playing),
+    ));
+    commands
+        .spawn((
+            orientation.frame(),
             BackgroundColor(Color::linear_rgb(0.5, 0.5, 0.5)),
             BorderRadius::all(Val::VMax(5.)),
+            GrabbedHud,
+            StateScoped(GameState::Playing),
         ))
         .with_child((
+            orientation.fill(settings.min_fill()),
+            BackgroundColor(NOT_CHARGING),
+            BorderRadius::all(Val::VMax(5.)),
+            PowerBar,
+        ));
+    commands
+        .spawn((
             Node {
                 position_type: PositionType::Absolute,
-                min_width: Val::VMax(MIN_FILL),
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            GrabbedHud,
+            StateScoped(GameState::Playing),
+        ))
+        .with_children(|crosshair| {
+            for (width, height) in [(16., 2.), (2., 16.)] {
+                crosshair.spawn((
+                    Node {
+                        position_type: PositionType::Absolute,
+                        width: Val::Px(width),
+                        height: Val::Px(height),
+                        ..Default::default()
+                    },
+                    BackgroundColor(CROSSHAIR),
+                ));
+            }
+        });
+}
+
+#[derive(Component)]
+struct StatsText;
+
+#[derive(Resource, Default)]
+struct SpawnStats {
+    total_spawned: usize,
+}
+
+fn count_spawns(mut events: EventReader<BallSpawn>, mut stats: ResMut<SpawnStats>) {
+    stats.total_spawned += events.read().count();
+}
+
+fn reset_balls(
+    balls: Query<Entity, With<Ball>>,
+    mut commands: Commands,
+    mut stats: ResMut<SpawnStats>,
+    mut live: ResMut<LiveBalls>,
+) {
+    for ball in &balls {
+        commands.entity(ball).despawn();
+    }
+    stats.total_spawned = 0;
+    live.0 = 0;
+}
+
+fn update_stats_text(
+    balls: Query<&Ball>,
+    stats: Res<SpawnStats>,
+    mut text: Single<&mut Text, With<StatsText>>,
+) {
+    text.0 = format!(
+        "Live: {}  Fired: {}",
+        balls.iter().count(),
+        stats.total_spawned
+    );
+}
+
+#[derive(Component)]
+struct GrabbedHud;
+
+fn show_grabbed_hud(
+    mut hud: Query<&mut Visibility, With<GrabbedHud>>,
+    window: Single<&Window, With<PrimaryWindow>>,
+) {
+    let visibility = if window.cursor_options.visible {
+        Visibility::Hidden
+    } else {
+        Visibility::Inherited
+    };
+    for mut hud in &mut hud {
+        hud.set_if_neq(visibility);
+    }
+}
+
+#[derive(Resource, Default, Clone, Copy)]
+enum PowerBarOrientation {
+    #[default]
+    Horizontal,
+    Vertical,
+}
+
+impl PowerBarOrientation {
+    fn frame(self) -> Node {
+        let (width, height) = match self {
+            PowerBarOrientation::Horizontal => (Val::VMax(30.), Val::VMax(5.)),
+            PowerBarOrientation::Vertical => (Val::VMax(5.), Val::VMax(30.)),
+        };
+        Node {
+            position_type: PositionType::Absolute,
+            width,
+            height,
+            bottom: Val::Px(20.),
+            left: Val::Px(20.),
+            ..Default::default()
+        }
+    }
+
+    fn fill(self, min_fill: f32) -> Node {
+        match self {
+            PowerBarOrientation::Horizontal => Node {
+                position_type: PositionType::Absolute,
+                min_width: Val::VMax(min_fill),
                 height: Val::Percent(95.),
                 margin: UiRect::all(Val::VMax(0.125)),
                 ..Default::default()
             },
-            BackgroundColor(NOT_CHARGING),
-            BorderRadius::all(Val::VMax(5.)),
-            PowerBar {
-                min: POWER_MIN,
-                max: POWER_MAX,
+            PowerBarOrientation::Vertical => Node {
+                position_type: PositionType::Absolute,
+                min_height: Val::VMax(min_fill),
+                width: Val::Percent(95.),
+                bottom: Val::Px(0.),
+                margin: UiRect::all(Val::VMax(0.125)),
+                ..Default::default()
             },
-        ));
+        }
+    }
+
+    fn set_fill(self, bar: &mut Node, fill: Val) {
+        match self {
+            PowerBarOrientation::Horizontal => bar.width = fill,
+            PowerBarOrientation::Vertical => bar.height = fill,
+        }
+    }
+}
+
+fn toggle_power_bar_orientation(mut orientation: ResMut<PowerBarOrientation>) {
+    *orientation = match *orientation {
+        PowerBarOrientation::Horizontal => PowerBarOrientation::Vertical,
+        PowerBarOrientation::Vertical => PowerBarOrientation::Horizontal,
+    };
+}
+
+fn layout_power_bar(
+    mut bars: Query<(&mut Node, &ChildOf), With<PowerBar>>,
+    mut frames: Query<&mut Node, Without<PowerBar>>,
+    orientation: Res<PowerBarOrientation>,
+    settings: Res<GameSettings>,
+) {
+    for (mut bar, child_of) in &mut bars {
+        *bar = orientation.fill(settings.min_fill());
+        if let Ok(mut frame) = frames.get_mut(child_of.parent()) {
+            *frame = orientation.frame();
+        }
+    }
 }
 
 fn update_power_bar(
-    mut bars: Query<(&mut Node, &PowerBar, &mut BackgroundColor)>,
+    mut bars: Query<(&mut Node, &mut BackgroundColor), With<PowerBar>>,
     power: Res<Power>,
+    orientation: Res<PowerBarOrientation>,
+    settings: Res<GameSettings>,
 ) {
-    for (mut bar, config, mut bg) in &mut bars {
+    let min_fill = settings.min_fill();
+    for (mut bar, mut bg) in &mut bars {
         if !power.charging {
             bg.0 = NOT_CHARGING;
-            bar.width = Val::VMax(MIN_FILL);
+            orientation.set_fill(&mut bar, Val::VMax(min_fill));
         } else {
-            let percent = (power.current - config.min) / (config.max - config.min);
+            let percent = (power.current - settings.power_min) / settings.power_range();
             bg.0 = Color::linear_rgb(1. - percent, percent, 0.);
-            bar.width = Val::VMax(MIN_FILL + percent * EMPTY_SPACE);
+            orientation.set_fill(
+                &mut bar,
+                Val::VMax(min_fill + percent * (BAR_LENGTH - min_fill)),
+            );
         }
     }
 }
@@ -186,15 +1111,20 @@ fn player_look(
     mut player: Single<&mut Transform, With<Player>>,
     mouse_motion: Res<AccumulatedMouseMotion>,
     window: Single<&Window, With<PrimaryWindow>>,
+    settings: Res<GameSettings>,
 ) {
     if !window.focused {
         return;
     }
-    let sensitivity = 100. / window.width().min(window.height()) * MOUSE_SENSITIVITY;
+    let sensitivity = 100. / window.width().min(window.height()) * settings.mouse_sensitivity;
 
     use EulerRot::YXZ;
     let (mut yaw, mut pitch, _) = player.rotation.to_euler(YXZ);
 
+    // `AccumulatedMouseMotion` only holds the motion since the previous frame,
+    // so applying it once per frame sums to the same rotation at any framerate:
+    // 240 small deltas and 60 large ones add up to the same mouse travel. Don't
+    // scale this by `time.delta_secs()`, that is what would make it FPS-dependent.
     pitch -= mouse_motion.delta.y * sensitivity;
     pitch = pitch.clamp(-PI / 2., PI / 2.);
     yaw -= mouse_motion.delta.x * sensitivity;
@@ -205,6 +1135,9 @@ fn player_look(
 fn player_move(
     mut player: Single<&mut Transform, With<Player>>,
     keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<GameSettings>,
+    arena: Res<Arena>,
+    scale: Res<WorldScale>,
     time: Res<Time>,
 ) {
     let mut direction = Vec3::ZERO;
@@ -229,10 +1162,17 @@ fn player_move(
     to_move.y = 0.;
     to_move = to_move.normalize_or_zero();
 
-    player.translation += to_move * time.delta_secs() * PLAYER_SPEED;
+    player.translation += to_move * time.delta_secs() * settings.player_speed * **scale;
+    let (min, max) = (arena.min * **scale, arena.max * **scale);
+    player.translation.x = player.translation.x.clamp(min.x, max.x);
+    player.translation.z = player.translation.z.clamp(min.z, max.z);
 }
 
-fn apply_grab(grab: Trigger<GrabEvent>, mut window: Single<&mut Window, With<PrimaryWindow>>) {
+fn apply_grab(
+    grab: Trigger<GrabEvent>,
+    mut window: Single<&mut Window, With<PrimaryWindow>>,
+    mut power: ResMut<Power>,
+) {
     use bevy::window::CursorGrabMode;
     if **grab {
         window.cursor_options.visible = false;
@@ -240,31 +1180,264 @@ fn apply_grab(grab: Trigger<GrabEvent>, mut window: Single<&mut Window, With<Pri
     } else {
         window.cursor_options.visible = true;
         window.cursor_options.grab_mode = CursorGrabMode::None;
+        power.burst = 0;
     }
 }
 
-fn focus_events(mut events: EventReader<WindowFocused>, mut commands: Commands) {
-    if let Some(event) = events.read().last() {
-        commands.trigger(GrabEvent(event.focused));
-    }
+fn cursor_grabbed(window: Single<&Window, With<PrimaryWindow>>) -> bool {
+    !window.cursor_options.visible
 }
 
-fn toggle_grab(mut window: Single<&mut Window, With<PrimaryWindow>>, mut commands: Commands) {
-    window.focused = !window.focused;
-    commands.trigger(GrabEvent(window.focused));
-}
+#[derive(Resource, PartialEq)]
+struct ClickToGrab(bool);
+
+fn click_to_grab(window: Single<&Window, With<PrimaryWindow>>, mut commands: Commands) {
+    if window.cursor_options.visible {
+        commands.trigger(GrabEvent(true));
+    }
+}
+
+fn focus_events(mut events: EventReader<WindowFocused>, mut commands: Commands) {
+    if let Some(event) = events.read().last() {
+        commands.trigger(GrabEvent(event.focused));
+    }
+}
+
+#[derive(Resource, Default, PartialEq)]
+struct AutoPauseOnBlur(bool);
+
+fn auto_pause_on_blur(
+    mut events: EventReader<WindowFocused>,
+    state: Res<State<GameState>>,
+    mut next: ResMut<NextState<GameState>>,
+    mut auto_paused: Local<bool>,
+) {
+    let Some(event) = events.read().last() else {
+        return;
+    };
+    match (state.get(), event.focused) {
+        (GameState::Playing, false) => {
+            next.set(GameState::Paused);
+            *auto_paused = true;
+        }
+        // Only undo our own pause; one the player chose stays put.
+        (GameState::Paused, true) if *auto_paused => {
+            next.set(GameState::Playing);
+            *auto_paused = false;
+        }
+        (_, true) => *auto_paused = false,
+        _ => {}
+    }
+}
+
+#[derive(States, Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum GameState {
+    #[default]
+    Menu,
+    Playing,
+    Paused,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct InGame;
+
+impl ComputedStates for InGame {
+    type SourceStates = GameState;
+
+    fn compute(state: GameState) -> Option<Self> {
+        match state {
+            GameState::Playing | GameState::Paused => Some(InGame),
+            GameState::Menu => None,
+        }
+    }
+}
+
+fn toggle_pause(state: Res<State<GameState>>, mut next: ResMut<NextState<GameState>>) {
+    match state.get() {
+        GameState::Playing => next.set(GameState::Paused),
+        GameState::Paused => next.set(GameState::Playing),
+        GameState::Menu => {}
+    }
+}
+
+fn resume(mut next: ResMut<NextState<GameState>>) {
+    next.set(GameState::Playing);
+}
+
+fn cancel_charge(mut power: ResMut<Power>) {
+    power.charging = false;
+    power.current = 1.;
+    power.held = 0.;
+    power.burst = 0;
+}
+
+fn spawn_pause_overlay(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(16.),
+                ..Default::default()
+            },
+            BackgroundColor(Color::linear_rgba(0., 0., 0., 0.5)),
+            StateScoped(GameState::Paused),
+        ))
+        .with_children(|overlay| {
+            overlay.spawn((
+                Text::new("Paused"),
+                TextFont {
+                    font_size: 64.,
+                    ..Default::default()
+                },
+            ));
+            overlay.spawn(Text::new(
+                "Esc or click to resume, . to step, Q for the menu",
+            ));
+        });
+}
+
+#[derive(Resource, Default, PartialEq)]
+struct StepOnce(bool);
+
+fn step_once(mut step: ResMut<StepOnce>) {
+    step.0 = true;
+}
+
+fn consume_step_once(mut step: ResMut<StepOnce>) {
+    step.0 = false;
+}
+
+fn quit_to_menu(mut next: ResMut<NextState<GameState>>) {
+    next.set(GameState::Menu);
+}
+
+#[derive(Component)]
+struct PlayButton;
+
+fn spawn_menu(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(40.),
+                ..Default::default()
+            },
+            StateScoped(GameState::Menu),
+        ))
+        .with_children(|menu| {
+            menu.spawn((
+                Text::new("Basic FPS"),
+                TextFont {
+                    font_size: 64.,
+                    ..Default::default()
+                },
+            ));
+            menu.spawn((
+                Button,
+                Node {
+                    padding: UiRect::axes(Val::Px(40.), Val::Px(12.)),
+                    ..Default::default()
+                },
+                BackgroundColor(BUTTON_IDLE),
+                BorderRadius::all(Val::Px(8.)),
+                PlayButton,
+            ))
+            .with_child(Text::new("Play"));
+        });
+}
+
+fn press_play(
+    button: Single<(Ref<Interaction>, &mut BackgroundColor), With<PlayButton>>,
+    mut next: ResMut<NextState<GameState>>,
+) {
+    let (interaction, mut bg) = button.into_inner();
+    if !interaction.is_changed() {
+        return;
+    }
+    match *interaction {
+        Interaction::Pressed => next.set(GameState::Playing),
+        Interaction::Hovered => bg.0 = BUTTON_HOVERED,
+        Interaction::None => bg.0 = BUTTON_IDLE,
+    }
+}
+
+fn release_grab(mut commands: Commands) {
+    commands.trigger(GrabEvent(false));
+}
+
+fn restore_grab(mut commands: Commands) {
+    commands.trigger(GrabEvent(true));
+}
 
 fn spawn_ball(
     mut events: EventReader<BallSpawn>,
     mut commands: Commands,
     ball_data: Res<BallData>,
+    settings: Res<GameSettings>,
+    fixed_color: Res<FixedBallColor>,
+    origin: Res<ShotOrigin>,
+    scale: Res<WorldScale>,
 ) {
     for spawn in events.read() {
+        let material = fixed_color
+            .and_then(|index| ball_data.materials.get(index).cloned())
+            .unwrap_or_else(|| ball_data.material());
         commands.spawn((
-            Transform::from_translation(spawn.position),
-            Mesh3d(ball_data.mesh()),
-            MeshMaterial3d(ball_data.material()),
-            Velocity(spawn.velocity * spawn.power * SHOT_VELOCITY),
+            ball_data.ball(
+                origin.resolve(spawn.position),
+                spawn.velocity * spawn.power * settings.shot_velocity * **scale,
+                material,
+            ),
+            GravityScale(settings.gravity_scale),
+        ));
+    }
+}
+
+#[derive(Resource, Default, Deref)]
+struct FixedBallColor(Option<usize>);
+
+fn cycle_ball_color(mut fixed_color: ResMut<FixedBallColor>, ball_data: Res<BallData>) {
+    fixed_color.0 = match fixed_color.0 {
+        None if !ball_data.materials.is_empty() => Some(0),
+        Some(index) if index + 1 < ball_data.materials.len() => Some(index + 1),
+        _ => None,
+    };
+}
+
+#[derive(Resource, Default, Deref)]
+struct InitialBalls(usize);
+
+fn spawn_initial_balls(
+    mut commands: Commands,
+    ball_data: Res<BallData>,
+    settings: Res<GameSettings>,
+    initial: Res<InitialBalls>,
+    scale: Res<WorldScale>,
+) {
+    use rand::Rng;
+    for _ in 0..**initial {
+        let (position, velocity) = {
+            let mut rng = ball_data.rng.lock().unwrap();
+            let position = Vec3::new(
+                rng.random_range(-40.0..40.),
+                rng.random_range(0.0..10.),
+                rng.random_range(-45.0..-5.),
+            );
+            let velocity = Vec3::new(rng.random_range(-1.0..1.), 0., rng.random_range(-1.0..1.));
+            (position * **scale, velocity * **scale)
+        };
+        commands.spawn((
+            ball_data.ball(position, velocity, ball_data.material()),
+            GravityScale(settings.gravity_scale),
         ));
     }
 }
@@ -273,33 +1446,138 @@ fn spawn_ball(
 struct Power {
     charging: bool,
     current: f32,
+    held: f32,
+    curve: ChargeCurve,
+    mode: ChargeMode,
+    burst: u32,
+    burst_cooldown: f32,
+}
+
+#[derive(Clone, Copy)]
+enum ChargeMode {
+    Power,
+    Count,
+}
+
+fn toggle_charge_mode(mut power: ResMut<Power>) {
+    power.mode = match power.mode {
+        ChargeMode::Power => ChargeMode::Count,
+        ChargeMode::Count => ChargeMode::Power,
+    };
+}
+
+#[derive(Default, Clone, Copy)]
+enum ChargeCurve {
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+}
+
+impl ChargeCurve {
+    fn shape(self, t: f32) -> f32 {
+        let t = t.clamp(0., 1.);
+        match self {
+            ChargeCurve::Linear => t,
+            ChargeCurve::EaseIn => t * t,
+            ChargeCurve::EaseOut => 1. - (1. - t) * (1. - t),
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            ChargeCurve::Linear => ChargeCurve::EaseIn,
+            ChargeCurve::EaseIn => ChargeCurve::EaseOut,
+            ChargeCurve::EaseOut => ChargeCurve::Linear,
+        }
+    }
+}
+
+fn cycle_charge_curve(mut power: ResMut<Power>) {
+    power.curve = power.curve.next();
+}
+
+#[derive(Resource, Default)]
+struct MouseFlick {
+    samples: std::collections::VecDeque<(f32, Vec2)>,
+}
+
+impl MouseFlick {
+    fn velocity(&self) -> Vec2 {
+        let total: Vec2 = self.samples.iter().map(|(_, delta)| *delta).sum();
+        total / FLICK_WINDOW
+    }
+}
+
+#[derive(Resource, Default, PartialEq)]
+struct FlickEnglish(bool);
+
+fn toggle_flick_english(mut english: ResMut<FlickEnglish>, mut flick: ResMut<MouseFlick>) {
+    english.0 = !english.0;
+    flick.samples.clear();
+}
+
+fn track_mouse_flick(
+    mut flick: ResMut<MouseFlick>,
+    mouse_motion: Res<AccumulatedMouseMotion>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs();
+    flick.samples.push_back((now, mouse_motion.delta));
+    while let Some(&(sampled, _)) = flick.samples.front() {
+        if now - sampled <= FLICK_WINDOW {
+            break;
+        }
+        flick.samples.pop_front();
+    }
 }
 
 fn shoot_ball(
     inputs: Res<ButtonInput<MouseButton>>,
     player: Single<&Transform, With<Player>>,
     mut spawner: EventWriter<BallSpawn>,
-    window: Single<&Window, With<PrimaryWindow>>,
     mut power: ResMut<Power>,
+    flick: Res<MouseFlick>,
+    settings: Res<GameSettings>,
     time: Res<Time>,
 ) {
-    if window.cursor_options.visible {
-        return;
+    let flick = flick.velocity() * FLICK_INFLUENCE;
+    let english = player.right().as_vec3() * flick.x - player.up().as_vec3() * flick.y;
+    let shot = |power| BallSpawn {
+        position: player.translation,
+        velocity: (player.forward().as_vec3() + english) * settings.shot_velocity,
+        power,
+    };
+
+    if power.burst > 0 {
+        power.burst_cooldown -= time.delta_secs();
+        if power.burst_cooldown <= 0. {
+            spawner.write(shot(BURST_POWER));
+            power.burst -= 1;
+            power.burst_cooldown += BURST_INTERVAL;
+        }
     }
 
     if power.charging {
         if inputs.just_released(MouseButton::Left) {
-            spawner.write(BallSpawn {
-                position: player.translation,
-                velocity: player.forward().as_vec3() * SHOT_VELOCITY,
-                power: power.current,
-            });
+            match power.mode {
+                ChargeMode::Power => {
+                    spawner.write(shot(power.current));
+                }
+                ChargeMode::Count => {
+                    let count = (power.held * BURST_BALLS_PER_SECOND).ceil() as u32;
+                    power.burst = count.clamp(1, MAX_BURST);
+                    power.burst_cooldown = 0.;
+                }
+            }
             power.charging = false;
             power.current = 1.;
+            power.held = 0.;
         }
         if inputs.pressed(MouseButton::Left) {
-            power.current += time.delta_secs();
-            power.current = power.current.clamp(POWER_MIN, POWER_MAX);
+            power.held += time.delta_secs();
+            let t = power.held / settings.power_range();
+            power.current = settings.power_min + power.curve.shape(t) * settings.power_range();
         }
     }
     if inputs.just_pressed(MouseButton::Left) {
@@ -307,14 +1585,866 @@ fn shoot_ball(
     }
 }
 
+#[derive(Resource, Default)]
+struct TrajectoryBounces(bool);
+
+fn toggle_trajectory_bounces(mut bounces: ResMut<TrajectoryBounces>) {
+    bounces.0 = !bounces.0;
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_trajectory(
+    mut gizmos: Gizmos,
+    power: Res<Power>,
+    player: Single<&Transform, With<Player>>,
+    origin: Res<ShotOrigin>,
+    settings: Res<GameSettings>,
+    bounces: Res<TrajectoryBounces>,
+    arena: ArenaBounce,
+    float_until_bounce: Res<FloatUntilBounce>,
+    scale: Res<WorldScale>,
+    fixed: Res<Time<Fixed>>,
+) {
+    if !power.charging {
+        return;
+    }
+    let dt = fixed.timestep().as_secs_f32();
+    let gravity = settings.gravity * settings.gravity_scale * **scale;
+    let mut position = origin.resolve(player.translation);
+    let mut velocity = player.forward().as_vec3()
+        * settings.shot_velocity
+        * power.current
+        * settings.shot_velocity
+        * **scale;
+    let mut points = vec![position];
+    let steps = if bounces.0 {
+        TRAJECTORY_MAX_STEPS
+    } else {
+        TRAJECTORY_POINTS
+    };
+    let mut bounced = false;
+    let mut restitution = 1.;
+    for _ in 0..steps {
+        if !float_until_bounce.0 || bounced {
+            velocity += gravity * dt;
+        }
+        position += velocity * dt;
+        let hit = arena.reflect(&mut position, &mut velocity, Some(&mut restitution));
+        bounced |= hit.floor;
+        points.push(position);
+        if (hit.any && !bounces.0) || (hit.floor && velocity.y == 0.) {
+            break;
+        }
+    }
+    let percent = ((power.current - settings.power_min) / settings.power_range()).clamp(0., 1.);
+    gizmos.linestrip(points, Color::linear_rgb(1. - percent, percent, 0.));
+}
+
+#[derive(Resource, Default, PartialEq)]
+struct ViewBounds(bool);
+
+fn toggle_view_bounds(mut bounds: ResMut<ViewBounds>) {
+    bounds.0 = !bounds.0;
+}
+
+fn contain_in_view(
+    mut balls: Query<(&Transform, &mut Velocity)>,
+    camera: Single<(&Transform, &Projection), With<Player>>,
+    scale: Res<WorldScale>,
+) {
+    let (camera, projection) = *camera;
+    let Projection::Perspective(perspective) = projection else {
+        return;
+    };
+    let vertical = perspective.fov / 2. + VIEW_BOUNDS_MARGIN;
+    let horizontal =
+        ((perspective.fov / 2.).tan() * perspective.aspect_ratio).atan() + VIEW_BOUNDS_MARGIN;
+    let planes = [
+        (Vec3::new(horizontal.cos(), 0., horizontal.sin()), 0.),
+        (Vec3::new(-horizontal.cos(), 0., horizontal.sin()), 0.),
+        (Vec3::new(0., vertical.cos(), vertical.sin()), 0.),
+        (Vec3::NEG_Z, VIEW_BOUNDS_DISTANCE * **scale),
+    ]
+    .map(|(normal, offset)| (camera.rotation * normal, offset));
+    for (transform, mut velocity) in &mut balls {
+        let local = transform.translation - camera.translation;
+        for (normal, offset) in planes {
+            let along = velocity.dot(normal);
+            if local.dot(normal) > offset && along > 0. {
+                **velocity -= 2. * along * normal;
+            }
+        }
+    }
+}
+
+#[derive(Resource, Default, PartialEq, Clone, Copy)]
+enum PhysicsSchedule {
+    #[default]
+    Fixed,
+    Variable,
+}
+
+fn toggle_physics_schedule(mut schedule: ResMut<PhysicsSchedule>) {
+    *schedule = match *schedule {
+        PhysicsSchedule::Fixed => PhysicsSchedule::Variable,
+        PhysicsSchedule::Variable => PhysicsSchedule::Fixed,
+    };
+}
+
 fn apply_velocity(mut objects: Query<(&mut Transform, &Velocity)>, time: Res<Time>) {
-    for (mut transform, velocity) in &mut objects {
-        transform.translation += velocity.0 * time.delta_secs();
+    let dt = time.delta_secs();
+    objects
+        .par_iter_mut()
+        .for_each(|(mut transform, velocity)| {
+            transform.translation += velocity.0 * dt;
+        });
+}
+
+#[derive(Component)]
+struct PowerBar;
+
+#[derive(Component)]
+struct TonemappingLabel(Timer);
+
+fn tonemapping_message(tonemapping: &Tonemapping, bloom: bool) -> String {
+    let bloom = if bloom { "on" } else { "off" };
+    format!("Tonemapping: {tonemapping:?} (bloom {bloom})")
+}
+
+fn cycle_tonemapping(
+    mut camera: Single<(&mut Tonemapping, Has<Bloom>), With<Player>>,
+    mut label: Single<&mut Text, With<TonemappingLabel>>,
+) {
+    let (tonemapping, bloom) = &mut *camera;
+    let current = TONEMAPPING_CYCLE
+        .iter()
+        .position(|t| *t == **tonemapping)
+        .unwrap_or(0);
+    **tonemapping = TONEMAPPING_CYCLE[(current + 1) % TONEMAPPING_CYCLE.len()];
+    label.0 = tonemapping_message(tonemapping, *bloom);
+}
+
+fn toggle_bloom(
+    mut commands: Commands,
+    mut camera: Single<(Entity, &mut Camera, &Tonemapping, Has<Bloom>), With<Player>>,
+    mut label: Single<&mut Text, With<TonemappingLabel>>,
+) {
+    let (entity, camera, tonemapping, bloom) = &mut *camera;
+    if *bloom {
+        commands.entity(*entity).remove::<Bloom>();
+    } else {
+        commands.entity(*entity).insert(Bloom::NATURAL);
+    }
+    camera.hdr = !*bloom;
+    label.0 = tonemapping_message(tonemapping, !*bloom);
+}
+
+fn fade_tonemapping_label(
+    mut label: Single<(Ref<Text>, &mut Visibility, &mut TonemappingLabel)>,
+    time: Res<Time>,
+) {
+    let (text, visibility, timer) = &mut *label;
+    if text.is_changed() && !text.is_added() {
+        **visibility = Visibility::Visible;
+        timer.0.reset();
+    }
+    if timer.0.tick(time.delta()).just_finished() {
+        **visibility = Visibility::Hidden;
+    }
+}
+
+#[derive(Resource)]
+struct LaserSight {
+    enabled: bool,
+    color: Color,
+    max_length: f32,
+}
+
+impl Default for LaserSight {
+    fn default() -> Self {
+        LaserSight {
+            enabled: true,
+            color: Color::linear_rgb(1., 0., 0.),
+            max_length: 100.,
+        }
+    }
+}
+
+#[derive(Resource, Deref)]
+struct WorldScale(f32);
+
+fn floor_hit(origin: Vec3, direction: Vec3, floor: f32) -> Option<f32> {
+    if direction.y >= 0. {
+        return None;
+    }
+    Some(((floor - origin.y) / direction.y).max(0.))
+}
+
+fn laser_segment(player: &Transform, laser: &LaserSight, floor: f32) -> (Vec3, Vec3) {
+    let origin = player.transform_point(LASER_ORIGIN);
+    let direction = player.forward().as_vec3();
+    let length =
+        floor_hit(origin, direction, floor).map_or(laser.max_length, |t| t.min(laser.max_length));
+    (origin, origin + direction * length)
+}
+
+fn toggle_laser_sight(mut laser: ResMut<LaserSight>) {
+    laser.enabled = !laser.enabled;
+}
+
+fn draw_laser_sight(
+    mut gizmos: Gizmos,
+    laser: Res<LaserSight>,
+    player: Single<&Transform, With<Player>>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    arena: Res<Arena>,
+    scale: Res<WorldScale>,
+) {
+    if !laser.enabled || window.cursor_options.visible {
+        return;
+    }
+    let (start, end) = laser_segment(&player, &laser, arena.min.y * **scale);
+    gizmos.line(start, end, laser.color);
+}
+
+#[derive(Resource, Default, PartialEq)]
+struct DebugGizmos(bool);
+
+fn toggle_debug_gizmos(mut debug: ResMut<DebugGizmos>) {
+    debug.0 = !debug.0;
+}
+
+#[derive(Component)]
+struct MuzzleFlash(Timer);
+
+fn mark_muzzle(
+    mut events: EventReader<BallSpawn>,
+    mut commands: Commands,
+    origin: Res<ShotOrigin>,
+) {
+    for spawn in events.read() {
+        commands.spawn((
+            Transform::from_translation(origin.resolve(spawn.position)),
+            MuzzleFlash(Timer::from_seconds(MUZZLE_FLASH_SECS, TimerMode::Once)),
+        ));
+    }
+}
+
+fn draw_muzzle_flashes(
+    mut flashes: Query<(Entity, &Transform, &mut MuzzleFlash)>,
+    mut gizmos: Gizmos,
+    mut commands: Commands,
+    time: Res<Time>,
+) {
+    for (entity, transform, mut flash) in &mut flashes {
+        if flash.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        let fade = flash.0.fraction_remaining();
+        gizmos.sphere(
+            Isometry3d::from_translation(transform.translation),
+            0.2,
+            Color::linear_rgba(1., 1., 0., fade),
+        );
+    }
+}
+
+#[derive(Component, Deref, DerefMut)]
+struct ViewOffset(Vec3);
+
+fn apply_charge_dip(
+    player: Single<(&mut Transform, &mut ViewOffset)>,
+    power: Res<Power>,
+    settings: Res<GameSettings>,
+) {
+    let (mut transform, mut offset) = player.into_inner();
+    transform.translation -= **offset;
+    let fraction = if power.charging {
+        ((power.current - settings.power_min) / settings.power_range()).clamp(0., 1.)
+    } else {
+        0.
+    };
+    **offset = Vec3::NEG_Y * MAX_CHARGE_DIP * fraction;
+    transform.translation += **offset;
+}
+
+#[derive(Resource, PartialEq)]
+struct BallShadows(bool);
+
+fn toggle_ball_shadows(mut shadows: ResMut<BallShadows>) {
+    shadows.0 = !shadows.0;
+}
+
+fn draw_ball_shadows(
+    balls: Query<&Transform, With<Velocity>>,
+    mut gizmos: Gizmos,
+    scale: Res<WorldScale>,
+) {
+    let flat = Quat::from_rotation_x(PI / 2.);
+    for transform in &balls {
+        let height = transform.translation.y.max(0.) / **scale;
+        let falloff = 1. / (1. + height * 0.1);
+        let center = Vec3::new(transform.translation.x, 0.01, transform.translation.z);
+        for ring in 1..=3 {
+            let radius = falloff * **scale * ring as f32 / 3.;
+            let alpha = falloff * 0.6 / ring as f32;
+            gizmos.circle(
+                Isometry3d::new(center, flat),
+                radius,
+                Color::linear_rgba(0., 0., 0., alpha),
+            );
+        }
+    }
+}
+
+#[derive(Resource)]
+struct ShotOrigin {
+    from_turret: bool,
+    turret_position: Vec3,
+}
+
+impl FromWorld for ShotOrigin {
+    fn from_world(world: &mut World) -> Self {
+        let scale = world.get_resource::<WorldScale>().map_or(1., |s| **s);
+        ShotOrigin {
+            from_turret: false,
+            turret_position: Vec3::new(0., 0., -4.) * scale,
+        }
+    }
+}
+
+impl ShotOrigin {
+    fn resolve(&self, player_position: Vec3) -> Vec3 {
+        if self.from_turret {
+            self.turret_position
+        } else {
+            player_position
+        }
     }
 }
 
 #[derive(Component)]
-struct PowerBar {
-    min: f32,
-    max: f32,
+struct Turret;
+
+fn spawn_turret(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    origin: Res<ShotOrigin>,
+    scale: Res<WorldScale>,
+) {
+    commands.spawn((
+        Transform::from_translation(origin.turret_position),
+        Mesh3d(meshes.add(Cuboid::from_size(Vec3::new(0.4, 0.4, 1.5) * **scale))),
+        MeshMaterial3d(
+            materials.add(StandardMaterial::from_color(Color::linear_rgb(
+                0.3, 0.3, 0.35,
+            ))),
+        ),
+        if origin.from_turret {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        },
+        Turret,
+        StateScoped(InGame),
+    ));
+}
+
+fn toggle_shot_origin(
+    mut origin: ResMut<ShotOrigin>,
+    mut turret: Single<&mut Visibility, With<Turret>>,
+) {
+    origin.from_turret = !origin.from_turret;
+    **turret = if origin.from_turret {
+        Visibility::Visible
+    } else {
+        Visibility::Hidden
+    };
+}
+
+fn aim_turret(
+    mut turret: Single<&mut Transform, With<Turret>>,
+    player: Single<&Transform, (With<Player>, Without<Turret>)>,
+) {
+    turret.rotation = player.rotation;
+}
+
+#[derive(Component)]
+struct OffscreenArrow;
+
+fn point_offscreen_arrows(
+    balls: Query<&Transform, (With<Velocity>, Without<OffscreenArrow>)>,
+    mut arrows: Query<(&mut Node, &mut Transform, &mut Visibility), With<OffscreenArrow>>,
+    camera: Single<(&Camera, &GlobalTransform), With<Player>>,
+    window: Single<&Window, With<PrimaryWindow>>,
+) {
+    let (camera, camera_transform) = *camera;
+    let eye = camera_transform.translation();
+    let to_local = camera_transform.rotation().inverse();
+
+    let mut offscreen: Vec<(f32, Vec2)> = balls
+        .iter()
+        .filter_map(|ball| {
+            let position = ball.translation;
+            let local = to_local * (position - eye);
+            let on_screen = local.z < 0.
+                && camera
+                    .world_to_ndc(camera_transform, position)
+                    .is_some_and(|ndc| ndc.x.abs() <= 1. && ndc.y.abs() <= 1.);
+            let direction = Vec2::new(local.x, -local.y).try_normalize()?;
+            (!on_screen).then_some((local.length_squared(), direction))
+        })
+        .collect();
+    offscreen.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let half = Vec2::new(window.width(), window.height()) / 2.;
+    let reach = half - OFFSCREEN_ARROW_INSET;
+    let mut targets = offscreen.into_iter();
+    for (mut node, mut transform, mut visibility) in &mut arrows {
+        let Some((_, direction)) = targets.next() else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        let t = (reach.x / direction.x.abs()).min(reach.y / direction.y.abs());
+        let at = half + direction * t;
+        node.left = Val::Px(at.x - 14.);
+        node.top = Val::Px(at.y - 3.);
+        transform.rotation = Quat::from_rotation_z(direction.y.atan2(direction.x));
+        *visibility = Visibility::Visible;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::time::TimeUpdateStrategy;
+    use std::time::Duration;
+
+    const DT: f32 = 1. / 60.;
+
+    fn physics_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins);
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+            DT,
+        )));
+        app.insert_resource(WorldScale(1.));
+        app.init_resource::<GameSettings>();
+        app.init_resource::<FloatUntilBounce>();
+        app.init_resource::<BounceSettings>();
+        app.init_resource::<BounceProfile>();
+        app.insert_resource(BounceGain(1.2));
+        app.init_resource::<Arena>();
+        app.add_systems(
+            Update,
+            (
+                apply_gravity,
+                apply_velocity.after(apply_gravity),
+                bounce.after(apply_velocity),
+            ),
+        );
+        app.update();
+        app
+    }
+
+    fn spawn_test_ball(app: &mut App, position: Vec3, velocity: Vec3) -> Entity {
+        app.world_mut()
+            .spawn((
+                Transform::from_translation(position),
+                Velocity(velocity),
+                HasBounced(false),
+                CurrentRestitution(1.),
+                Ball,
+            ))
+            .id()
+    }
+
+    fn apexes(app: &mut App, ball: Entity, count: usize) -> Vec<f32> {
+        let mut apexes = Vec::new();
+        let mut top = None;
+        for _ in 0..100_000 {
+            app.update();
+            let y = app.world().get::<Transform>(ball).unwrap().translation.y;
+            let rising = app.world().get::<Velocity>(ball).unwrap().y > 0.;
+            match (rising, top) {
+                (true, _) => top = Some(top.map_or(y, |top: f32| top.max(y))),
+                (false, Some(peak)) => {
+                    apexes.push(peak.max(y));
+                    top = None;
+                    if apexes.len() == count {
+                        break;
+                    }
+                }
+                (false, None) => {}
+            }
+        }
+        apexes
+    }
+
+    fn residual_overlap(iterations: u32) -> f32 {
+        let mut app = App::new();
+        app.insert_resource(WorldScale(1.));
+        app.insert_resource(CollisionIterations(iterations));
+        app.add_systems(Update, ball_collisions);
+        for x in 0..4 {
+            for y in 0..4 {
+                for z in 0..4 {
+                    let position = Vec3::new(x as f32, y as f32, z as f32) * 1.2;
+                    spawn_test_ball(&mut app, position, Vec3::ZERO);
+                }
+            }
+        }
+        app.update();
+
+        let positions: Vec<Vec3> = app
+            .world_mut()
+            .query_filtered::<&Transform, With<Ball>>()
+            .iter(app.world())
+            .map(|transform| transform.translation)
+            .collect();
+        let mut overlap = 0.;
+        for (i, a) in positions.iter().enumerate() {
+            for b in &positions[i + 1..] {
+                overlap += (2. - a.distance(*b)).max(0.);
+            }
+        }
+        overlap
+    }
+
+    fn apply_gravity_serial(
+        mut objects: Query<(&mut Velocity, Option<&GravityScale>)>,
+        settings: Res<GameSettings>,
+        time: Res<Time>,
+    ) {
+        let g = settings.gravity * time.delta_secs();
+        for (mut v, scale) in &mut objects {
+            **v += g * scale.map_or(1., |s| **s);
+        }
+    }
+
+    fn apply_velocity_serial(mut objects: Query<(&mut Transform, &Velocity)>, time: Res<Time>) {
+        let dt = time.delta_secs();
+        for (mut transform, velocity) in &mut objects {
+            transform.translation += velocity.0 * dt;
+        }
+    }
+
+    fn integrate_many(
+        parallel: bool,
+        balls: usize,
+        ticks: usize,
+    ) -> (Vec<(Entity, Vec3)>, Duration) {
+        use rand::Rng;
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins);
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+            DT,
+        )));
+        app.insert_resource(WorldScale(1.));
+        app.init_resource::<GameSettings>();
+        app.init_resource::<FloatUntilBounce>();
+        if parallel {
+            app.add_systems(Update, (apply_gravity, apply_velocity.after(apply_gravity)));
+        } else {
+            app.add_systems(
+                Update,
+                (
+                    apply_gravity_serial,
+                    apply_velocity_serial.after(apply_gravity_serial),
+                ),
+            );
+        }
+        let mut rng = rand::rngs::StdRng::seed_from_u64(216);
+        for _ in 0..balls {
+            let mut random = || {
+                Vec3::new(
+                    rng.random_range(-50.0..50.),
+                    rng.random_range(0.0..50.),
+                    rng.random_range(-50.0..50.),
+                )
+            };
+            let (position, velocity) = (random(), random());
+            spawn_test_ball(&mut app, position, velocity);
+        }
+        app.update();
+
+        let start = std::time::Instant::now();
+        for _ in 0..ticks {
+            app.update();
+        }
+        let elapsed = start.elapsed();
+        let positions = app
+            .world_mut()
+            .query::<(Entity, &Transform)>()
+            .iter(app.world())
+            .map(|(entity, transform)| (entity, transform.translation))
+            .collect();
+        (positions, elapsed)
+    }
+
+    #[test]
+    fn parallel_integration_matches_serial() {
+        let (serial, _) = integrate_many(false, 300, 120);
+        let (parallel, _) = integrate_many(true, 300, 120);
+        assert_eq!(serial, parallel);
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with --ignored --nocapture"]
+    fn parallel_integration_benchmark() {
+        let (_, serial_time) = integrate_many(false, 5000, 300);
+        let (_, parallel_time) = integrate_many(true, 5000, 300);
+        println!("5000 balls x 300 ticks: serial {serial_time:?}, parallel {parallel_time:?}");
+    }
+
+    #[test]
+    fn empty_palette_falls_back_to_default_material() {
+        let fallback = bevy::asset::weak_handle!("b0a1b0a1-0000-4000-8000-00000000ba11");
+        let ball_data = BallData::new(Handle::default(), Vec::new(), fallback.clone());
+        assert_eq!(ball_data.material(), fallback);
+
+        let mut app = App::new();
+        app.add_event::<BallSpawn>();
+        app.insert_resource(ball_data);
+        app.insert_resource(WorldScale(1.));
+        app.init_resource::<GameSettings>();
+        app.init_resource::<FixedBallColor>();
+        app.init_resource::<ShotOrigin>();
+        app.add_systems(Update, spawn_ball);
+        app.world_mut().send_event(BallSpawn {
+            position: Vec3::ZERO,
+            velocity: Vec3::NEG_Z,
+            power: 1.,
+        });
+        app.update();
+
+        let materials: Vec<_> = app
+            .world_mut()
+            .query_filtered::<&MeshMaterial3d<StandardMaterial>, With<Ball>>()
+            .iter(app.world())
+            .map(|material| material.0.clone())
+            .collect();
+        assert_eq!(materials, vec![fallback]);
+    }
+
+    #[test]
+    fn blur_pauses_and_focus_resumes_only_an_auto_pause() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, bevy::state::app::StatesPlugin));
+        app.add_event::<WindowFocused>();
+        app.init_state::<GameState>();
+        app.add_systems(Update, auto_pause_on_blur);
+        let focus = |app: &mut App, focused| {
+            app.world_mut().send_event(WindowFocused {
+                window: Entity::PLACEHOLDER,
+                focused,
+            });
+            app.update();
+            app.update();
+            *app.world().resource::<State<GameState>>().get()
+        };
+
+        app.world_mut()
+            .resource_mut::<NextState<GameState>>()
+            .set(GameState::Playing);
+        app.update();
+        assert_eq!(focus(&mut app, false), GameState::Paused);
+        assert_eq!(focus(&mut app, true), GameState::Playing);
+
+        app.world_mut()
+            .resource_mut::<NextState<GameState>>()
+            .set(GameState::Paused);
+        app.update();
+        assert_eq!(focus(&mut app, false), GameState::Paused);
+        assert_eq!(focus(&mut app, true), GameState::Paused);
+    }
+
+    #[derive(Resource, Default)]
+    struct Pops(usize);
+
+    fn count_pops(mut events: EventReader<BallRested>, mut pops: ResMut<Pops>) {
+        pops.0 += events.read().count();
+    }
+
+    #[test]
+    fn resting_ball_pops_exactly_once() {
+        let mut app = physics_app();
+        app.add_event::<BallRested>();
+        app.insert_resource(BallCap {
+            max_live: None,
+            max_age: 60.,
+        });
+        app.init_resource::<LiveBalls>();
+        app.init_resource::<Pops>();
+        app.add_systems(
+            Update,
+            (despawn_balls.after(bounce), count_pops.after(despawn_balls)),
+        );
+        let ball = spawn_test_ball(&mut app, Vec3::ZERO, Vec3::ZERO);
+        app.world_mut().entity_mut(ball).insert((
+            BallLifetime::default(),
+            RestTimer(Timer::from_seconds(REST_SECS, TimerMode::Once)),
+        ));
+
+        for _ in 0..((REST_SECS + 1.) / DT) as usize {
+            app.update();
+        }
+        assert_eq!(app.world().resource::<Pops>().0, 1);
+        assert!(app.world().get_entity(ball).is_err());
+    }
+
+    #[test]
+    fn more_collision_iterations_reduce_overlap() {
+        let single = residual_overlap(1);
+        let many = residual_overlap(8);
+        assert!(many < single * 0.5, "{many} vs {single}");
+    }
+
+    #[test]
+    fn gravity_scale_changes_arc_height() {
+        let first_apex = |gravity_scale| {
+            let mut app = physics_app();
+            let ball = spawn_test_ball(&mut app, Vec3::ZERO, Vec3::new(2., 10., 0.));
+            app.world_mut()
+                .entity_mut(ball)
+                .insert(GravityScale(gravity_scale));
+            apexes(&mut app, ball, 1)[0]
+        };
+        let floaty = first_apex(0.5);
+        let heavy = first_apex(2.);
+        assert!(floaty > heavy);
+        assert!((floaty / heavy - 4.).abs() < 0.2, "{floaty} / {heavy}");
+    }
+
+    #[test]
+    fn charge_curves_at_midpoint() {
+        assert_eq!(ChargeCurve::Linear.shape(0.5), 0.5);
+        assert_eq!(ChargeCurve::EaseIn.shape(0.5), 0.25);
+        assert_eq!(ChargeCurve::EaseOut.shape(0.5), 0.75);
+    }
+
+    #[test]
+    fn float_until_bounce_holds_gravity_until_floor_contact() {
+        let mut app = physics_app();
+        app.insert_resource(FloatUntilBounce(true));
+        let ball = spawn_test_ball(&mut app, Vec3::Y * 5., Vec3::NEG_Y * 5.);
+        for _ in 0..30 {
+            app.update();
+            assert_eq!(app.world().get::<Velocity>(ball).unwrap().y, -5.);
+        }
+
+        for _ in 0..600 {
+            if app.world().get::<HasBounced>(ball).unwrap().0 {
+                break;
+            }
+            app.update();
+        }
+        assert!(app.world().get::<HasBounced>(ball).unwrap().0);
+        let after_bounce = app.world().get::<Velocity>(ball).unwrap().y;
+        app.update();
+        assert!(app.world().get::<Velocity>(ball).unwrap().y < after_bounce);
+    }
+
+    #[test]
+    fn bounce_height_ratio_is_scale_invariant() {
+        let apexes_at = |scale: f32| {
+            let mut app = physics_app();
+            app.insert_resource(WorldScale(scale));
+            let ball = spawn_test_ball(&mut app, Vec3::ZERO, Vec3::new(0., 10., 0.) * scale);
+            apexes(&mut app, ball, 3)
+        };
+        let unit = apexes_at(1.);
+        let scaled = apexes_at(3.);
+        for (unit, scaled) in unit.windows(2).zip(scaled.windows(2)) {
+            let (unit, scaled) = (unit[1] / unit[0], scaled[1] / scaled[0]);
+            assert!((unit - scaled).abs() < 0.01, "{unit} vs {scaled}");
+        }
+        assert!(
+            (scaled[0] / unit[0] - 3.).abs() < 0.05,
+            "{scaled:?} vs {unit:?}"
+        );
+    }
+
+    #[test]
+    fn bounce_gain_raises_apexes_until_the_speed_clamp() {
+        let mut app = physics_app();
+        app.insert_resource(BounceProfile::Gain);
+        let ball = spawn_test_ball(&mut app, Vec3::ZERO, Vec3::new(0., 300., 0.));
+        let apexes = apexes(&mut app, ball, 7);
+        assert!(apexes[..5].windows(2).all(|w| w[1] > w[0]), "{apexes:?}");
+
+        let settings = GameSettings::default();
+        let cap = settings.max_launch_speed();
+        let clamped = cap * cap / settings.gravity.length() / 2.;
+        for apex in &apexes[4..] {
+            assert!((apex / clamped - 1.).abs() < 0.01, "{apexes:?}");
+        }
+    }
+
+    #[test]
+    fn decay_shrinks_apexes_geometrically_on_floor_hits_only() {
+        let mut app = physics_app();
+        app.insert_resource(BounceProfile::Decay);
+        let ball = spawn_test_ball(&mut app, Vec3::ZERO, Vec3::new(0., 40., 0.));
+        let apexes = apexes(&mut app, ball, 4);
+        let ratios: Vec<f32> = apexes.windows(2).map(|w| w[1] / w[0]).collect();
+        for w in ratios.windows(2) {
+            let decay = w[1] / w[0];
+            let expected = RESTITUTION_DECAY * RESTITUTION_DECAY;
+            assert!((decay / expected - 1.).abs() < 0.05, "{apexes:?}");
+        }
+
+        let mut app = physics_app();
+        app.insert_resource(BounceProfile::Decay);
+        let ball = spawn_test_ball(&mut app, Vec3::new(-59.9, 20., 0.), Vec3::NEG_X * 10.);
+        for _ in 0..3 {
+            app.update();
+        }
+        assert!(app.world().get::<Velocity>(ball).unwrap().x > 0.);
+        assert_eq!(**app.world().get::<CurrentRestitution>(ball).unwrap(), 1.);
+    }
+
+    #[test]
+    fn head_on_balls_swap_velocities() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins);
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+            DT,
+        )));
+        app.insert_resource(WorldScale(1.));
+        app.init_resource::<CollisionIterations>();
+        app.add_systems(
+            Update,
+            (apply_velocity, ball_collisions.after(apply_velocity)),
+        );
+        let speed = Vec3::X * 3.;
+        let left = spawn_test_ball(&mut app, Vec3::new(-5., 1., 0.), speed);
+        let right = spawn_test_ball(&mut app, Vec3::new(5., 1., 0.), -speed);
+        let velocity = |app: &App, ball: Entity| **app.world().get::<Velocity>(ball).unwrap();
+
+        for _ in 0..300 {
+            if velocity(&app, left) != speed {
+                break;
+            }
+            app.update();
+        }
+        assert_eq!(velocity(&app, left), -speed);
+        assert_eq!(velocity(&app, right), speed);
+    }
+
+    #[test]
+    fn laser_aimed_down_stops_at_the_floor() {
+        let laser = LaserSight::default();
+        let eye = Transform::from_xyz(0., EYE_HEIGHT, 0.);
+        let down = eye.looking_to(Vec3::new(0., -1., -2.), Vec3::Y);
+        let (start, end) = laser_segment(&down, &laser, 0.);
+        assert!(start.distance(end) < laser.max_length);
+        assert!(end.y.abs() < 1e-4, "{end}");
+
+        let level = eye.looking_to(Vec3::NEG_Z, Vec3::Y);
+        let (start, end) = laser_segment(&level, &laser, 0.);
+        assert!((start.distance(end) - laser.max_length).abs() < 1e-3);
+    }
 }